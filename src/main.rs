@@ -1,6 +1,7 @@
 use std::{
+    collections::HashMap,
     sync::{
-        atomic::{AtomicUsize, Ordering},
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
         Mutex,
     },
     thread,
@@ -9,6 +10,465 @@ use std::{
 
 use clap::{Parser, Subcommand};
 
+/// Default number of entries in a transposition table. Each entry is tiny, so
+/// a few million entries keeps the memory footprint bounded while still
+/// covering the bulk of the repeated subtrees seen in practice.
+const TT_CAPACITY: usize = 1 << 22;
+
+/// The fixed-width bitset state threaded through the search.
+///
+/// The engine is generic over this trait so that decks larger than 128 cards
+/// become searchable. `u128` implements it directly as the fast specialization
+/// for decks of at most 128 cards, so existing performance doesn't regress;
+/// [`Wide`] backs larger decks with a `[u64; W]` bitset and a `[u8; S]`
+/// difference histogram, enumerating set bits one word at a time via
+/// trailing-zero iteration rather than testing one bit at a time.
+///
+/// `Hand` is the hand bitvector and `Diffs` is the difference histogram, where
+/// `Diffs[i]` is the number of pairs of cards in the hand whose XOR is `i`. The
+/// two always travel together, so a single width is selected at runtime from
+/// `cards_in_deck` (see [`search_command`]).
+trait Backend {
+    /// The hand bitvector.
+    type Hand: Clone + Send + Sync;
+    /// The difference histogram.
+    type Diffs: Clone + Send + Sync;
+
+    /// An empty hand.
+    fn empty_hand() -> Self::Hand;
+    /// A zeroed difference histogram.
+    fn empty_diffs() -> Self::Diffs;
+    /// Build a hand from a `u128` bitmask (used for seeds, which are at most 128
+    /// cards wide however large the deck is).
+    fn from_u128(bits: u128) -> Self::Hand;
+    /// A hand with the lowest `n` cards set, used as a placeholder default
+    /// result before the search finds anything.
+    fn low_bits(n: usize) -> Self::Hand;
+
+    /// The index of the highest card in the hand, or `None` if it is empty.
+    fn highest(hand: &Self::Hand) -> Option<usize>;
+    /// Whether card `i` is in the hand.
+    fn contains(hand: &Self::Hand, i: usize) -> bool;
+    /// The hand with card `i` added.
+    fn with_card(hand: &Self::Hand, i: usize) -> Self::Hand;
+    /// The indices of the cards in the hand, in increasing order.
+    fn cards(hand: &Self::Hand) -> Vec<usize>;
+
+    /// Try to add the card at `next_index`: for every card `i` already in the
+    /// hand, bump `differences[i ^ next_index]`, accumulating the prior values
+    /// into `quads` (which triple-counts, since each quad shows up three times).
+    /// Returns the updated histogram and quad count, or `None` if that would
+    /// push any entry past `max_diff_count`.
+    fn add_card(
+        hand: &Self::Hand,
+        diffs: &Self::Diffs,
+        next_index: usize,
+        max_diff_count: usize,
+        quads: u64,
+    ) -> Option<(Self::Diffs, u64)>;
+
+    /// The triple-counted quad count after placing card `j` onto the hand,
+    /// folded into `quads`. Only cards below `next_index` (i.e. every card in
+    /// the hand) contribute. `j` is never already in the hand.
+    fn quads_adding(
+        hand: &Self::Hand,
+        diffs: &Self::Diffs,
+        j: usize,
+        next_index: usize,
+        quads: u64,
+    ) -> u64;
+
+    /// The nonzero entries of `differences` for XOR values below `limit`, the
+    /// raw material for a canonical [`canonical_key`] signature.
+    fn signature(diffs: &Self::Diffs, limit: usize) -> Vec<u8>;
+}
+
+/// The fast specialization for decks of at most 128 cards: a `u128` hand and a
+/// `[u8; 128]` difference histogram, matching the original engine exactly.
+#[derive(Clone, Copy)]
+struct U128;
+
+impl Backend for U128 {
+    type Hand = u128;
+    type Diffs = [u8; 128];
+
+    fn empty_hand() -> u128 {
+        0
+    }
+
+    fn empty_diffs() -> [u8; 128] {
+        [0; 128]
+    }
+
+    fn from_u128(bits: u128) -> u128 {
+        bits
+    }
+
+    fn low_bits(n: usize) -> u128 {
+        if n >= 128 {
+            u128::MAX
+        } else {
+            (1u128 << n) - 1
+        }
+    }
+
+    fn highest(hand: &u128) -> Option<usize> {
+        hand.checked_ilog2().map(|x| x as usize)
+    }
+
+    fn contains(hand: &u128, i: usize) -> bool {
+        (hand >> i) & 1 == 1
+    }
+
+    fn with_card(hand: &u128, i: usize) -> u128 {
+        hand | (1u128 << i)
+    }
+
+    fn cards(hand: &u128) -> Vec<usize> {
+        (0..128).filter(|&i| (hand >> i) & 1 == 1).collect()
+    }
+
+    fn add_card(
+        hand: &u128,
+        diffs: &[u8; 128],
+        next_index: usize,
+        max_diff_count: usize,
+        quads: u64,
+    ) -> Option<([u8; 128], u64)> {
+        let mut diffs2 = *diffs;
+        let mut quads2 = quads;
+        for i in 0..next_index {
+            if (hand >> i) & 1 == 1 {
+                let difference = i ^ next_index;
+                // If there's a pair of cards in the hand with XOR x, and you add
+                // a new card which has XOR x with some other card in the hand,
+                // then those four cards form a quad. This counts each quad three
+                // times, so we divide by three later.
+                quads2 += diffs2[difference] as u64;
+                diffs2[difference] += 1;
+                // Don't try adding this card if that would violate max_diff_count.
+                if diffs2[difference] as usize > max_diff_count {
+                    return None;
+                }
+            }
+        }
+        Some((diffs2, quads2))
+    }
+
+    fn quads_adding(hand: &u128, diffs: &[u8; 128], j: usize, next_index: usize, quads: u64) -> u64 {
+        let mut quads2 = quads;
+        for i in 0..next_index {
+            if (hand >> i) & 1 == 1 {
+                quads2 += diffs[i ^ j] as u64;
+            }
+        }
+        quads2
+    }
+
+    fn signature(diffs: &[u8; 128], limit: usize) -> Vec<u8> {
+        diffs[..limit.min(128)]
+            .iter()
+            .copied()
+            .filter(|&count| count != 0)
+            .collect()
+    }
+}
+
+/// The general backend for decks larger than 128 cards: a `[u64; W]` bitset and
+/// a `[u8; S]` difference histogram, where `S` is a power of two at least as
+/// large as the deck. The hot loops walk the hand one `u64` lane at a time,
+/// enumerating set bits with trailing-zero iteration.
+#[derive(Clone, Copy)]
+struct Wide<const W: usize, const S: usize>;
+
+impl<const W: usize, const S: usize> Backend for Wide<W, S> {
+    type Hand = [u64; W];
+    type Diffs = [u8; S];
+
+    fn empty_hand() -> [u64; W] {
+        [0; W]
+    }
+
+    fn empty_diffs() -> [u8; S] {
+        [0; S]
+    }
+
+    fn from_u128(bits: u128) -> [u64; W] {
+        let mut hand = [0u64; W];
+        hand[0] = bits as u64;
+        if W > 1 {
+            hand[1] = (bits >> 64) as u64;
+        }
+        hand
+    }
+
+    fn low_bits(n: usize) -> [u64; W] {
+        let mut hand = [0u64; W];
+        for i in 0..n {
+            hand[i / 64] |= 1u64 << (i % 64);
+        }
+        hand
+    }
+
+    fn highest(hand: &[u64; W]) -> Option<usize> {
+        for word in (0..W).rev() {
+            if hand[word] != 0 {
+                return Some(word * 64 + 63 - hand[word].leading_zeros() as usize);
+            }
+        }
+        None
+    }
+
+    fn contains(hand: &[u64; W], i: usize) -> bool {
+        (hand[i / 64] >> (i % 64)) & 1 == 1
+    }
+
+    fn with_card(hand: &[u64; W], i: usize) -> [u64; W] {
+        let mut hand = *hand;
+        hand[i / 64] |= 1u64 << (i % 64);
+        hand
+    }
+
+    fn cards(hand: &[u64; W]) -> Vec<usize> {
+        let mut cards = Vec::new();
+        for (word, lane) in hand.iter().enumerate() {
+            let mut bits = *lane;
+            while bits != 0 {
+                cards.push(word * 64 + bits.trailing_zeros() as usize);
+                bits &= bits - 1;
+            }
+        }
+        cards
+    }
+
+    fn add_card(
+        hand: &[u64; W],
+        diffs: &[u8; S],
+        next_index: usize,
+        max_diff_count: usize,
+        quads: u64,
+    ) -> Option<([u8; S], u64)> {
+        let mut diffs2 = *diffs;
+        let mut quads2 = quads;
+        for (word, lane) in hand.iter().enumerate() {
+            let mut bits = *lane;
+            while bits != 0 {
+                let i = word * 64 + bits.trailing_zeros() as usize;
+                bits &= bits - 1;
+                let difference = i ^ next_index;
+                quads2 += diffs2[difference] as u64;
+                diffs2[difference] += 1;
+                if diffs2[difference] as usize > max_diff_count {
+                    return None;
+                }
+            }
+        }
+        Some((diffs2, quads2))
+    }
+
+    fn quads_adding(
+        hand: &[u64; W],
+        diffs: &[u8; S],
+        j: usize,
+        _next_index: usize,
+        quads: u64,
+    ) -> u64 {
+        let mut quads2 = quads;
+        for (word, lane) in hand.iter().enumerate() {
+            let mut bits = *lane;
+            while bits != 0 {
+                let i = word * 64 + bits.trailing_zeros() as usize;
+                bits &= bits - 1;
+                quads2 += diffs[i ^ j] as u64;
+            }
+        }
+        quads2
+    }
+
+    fn signature(diffs: &[u8; S], limit: usize) -> Vec<u8> {
+        diffs[..limit.min(S)]
+            .iter()
+            .copied()
+            .filter(|&count| count != 0)
+            .collect()
+    }
+}
+
+/// Build a canonical key for a search node.
+///
+/// The number of quads a future card can create depends only on the histogram
+/// of pairwise XOR-differences inside the affine subspace currently in use, not
+/// on which specific card indices produced them: the `GL(n, 2)` automorphism
+/// group of the space makes any two states with identical sorted difference
+/// histograms (and identical `cards_to_add` / `max_diff_count`) interchangeable.
+/// We therefore hash the multiset of nonzero entries of `differences` restricted
+/// to XOR values `< max_useful_card`, sorted so that relabeling dimensions
+/// produces the same key, together with `cards_to_add` and `max_diff_count`.
+fn canonical_key<B: Backend>(
+    differences: &B::Diffs,
+    max_useful_card: usize,
+    cards_to_add: usize,
+    max_diff_count: usize,
+) -> u64 {
+    let mut signature = B::signature(differences, max_useful_card);
+    signature.sort_unstable();
+    // FNV-1a over the sorted signature and the two scalar parameters. This is
+    // cheap and good enough to scatter keys across the table.
+    let mut hash = 0xcbf29ce484222325u64;
+    let mut mix = |byte: u8| {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    };
+    for byte in signature {
+        mix(byte);
+    }
+    for scalar in [cards_to_add as u64, max_diff_count as u64] {
+        for byte in scalar.to_le_bytes() {
+            mix(byte);
+        }
+    }
+    hash
+}
+
+/// An entry in the maximize-mode transposition table.
+#[derive(Clone, Copy)]
+struct Entry {
+    /// The `cards_to_add` the bound was computed at. A deeper entry (more cards
+    /// left to add) is an upper bound for any shallower query, since adding more
+    /// cards can only create more quads.
+    depth: usize,
+    /// The best *additional* quads obtainable from the node, relabeling-invariant
+    /// and therefore reusable for every state that shares this key.
+    bound: u64,
+}
+
+/// A bounded, depth-preferred transposition table for maximize-mode search.
+struct TranspositionTable {
+    map: HashMap<u64, Entry>,
+    capacity: usize,
+}
+
+impl TranspositionTable {
+    fn new(capacity: usize) -> Self {
+        Self {
+            map: HashMap::new(),
+            capacity,
+        }
+    }
+
+    fn get(&self, key: u64) -> Option<Entry> {
+        self.map.get(&key).copied()
+    }
+
+    /// Insert an entry, keeping the deepest bound for each key and refusing to
+    /// grow past the capacity (a full table simply stops accepting new keys).
+    fn insert(&mut self, key: u64, entry: Entry) {
+        match self.map.get_mut(&key) {
+            Some(existing) => {
+                if entry.depth > existing.depth
+                    || (entry.depth == existing.depth && entry.bound > existing.bound)
+                {
+                    *existing = entry;
+                }
+            }
+            None => {
+                if self.map.len() < self.capacity {
+                    self.map.insert(key, entry);
+                }
+            }
+        }
+    }
+}
+
+/// The global best result, shared between the workers of a parallel search.
+///
+/// Every worker prunes against the best result found by any other worker, as in
+/// parallel game-tree search: the atomic `score` is read on the maximize cutoff
+/// and the transposition-table prune, and `found` lets a target-quad search
+/// unwind every worker as soon as one of them hits the target.
+struct SharedBest<B: Backend> {
+    /// When maximizing, the highest number of quads found so far; when searching
+    /// for a target, the max card used by the first solution found (the search
+    /// short-circuits on it, so it is not necessarily the minimal max card).
+    score: AtomicU64,
+    /// The hand that produced `score`.
+    hand: Mutex<B::Hand>,
+    /// Set once a target-quad solution has been found, so the other workers can
+    /// observe it and stop early.
+    found: AtomicBool,
+}
+
+/// A deferred search node, handed to a worker thread to explore independently.
+struct Task<B: Backend> {
+    hand: B::Hand,
+    differences: B::Diffs,
+    next_index: usize,
+    cards_to_add: usize,
+    quads: u64,
+}
+
+impl<B: Backend> Clone for Task<B> {
+    fn clone(&self) -> Self {
+        Self {
+            hand: self.hand.clone(),
+            differences: self.differences.clone(),
+            next_index: self.next_index,
+            cards_to_add: self.cards_to_add,
+            quads: self.quads,
+        }
+    }
+}
+
+/// The initial search state, either empty or derived from a locked seed hand.
+///
+/// A seed pins a known-good set of cards into the hand, prefilling the
+/// difference histogram and quad count and advancing `next_index` past the
+/// highest locked card, so the search only explores extensions of that prefix.
+struct Prefix<B: Backend> {
+    hand: B::Hand,
+    differences: B::Diffs,
+    quads: u64,
+    next_index: usize,
+    cards_to_add: usize,
+}
+
+impl<B: Backend> Prefix<B> {
+    /// The empty prefix: an unconstrained search for a hand of `cards_in_hand`.
+    fn empty(cards_in_hand: usize) -> Self {
+        Self {
+            hand: B::empty_hand(),
+            differences: B::empty_diffs(),
+            quads: 0,
+            next_index: 0,
+            cards_to_add: cards_in_hand,
+        }
+    }
+
+    /// A prefix that locks the cards of `seed` into place.
+    fn from_seed(seed: u128, cards_in_hand: usize) -> Self {
+        let cards = B::cards(&B::from_u128(seed));
+        // Replay the locked cards through the same incremental bookkeeping the
+        // search uses, so the histogram and (triple-counted) quad count match.
+        let mut hand = B::empty_hand();
+        let mut differences = B::empty_diffs();
+        let mut quads = 0;
+        for &card in &cards {
+            let (differences2, quads2) =
+                B::add_card(&hand, &differences, card, usize::MAX, quads).unwrap();
+            differences = differences2;
+            quads = quads2;
+            hand = B::with_card(&hand, card);
+        }
+        Self {
+            hand,
+            differences,
+            quads,
+            next_index: cards.last().map_or(0, |&top| top + 1),
+            cards_to_add: cards_in_hand - cards.len(),
+        }
+    }
+}
+
 /// Search for a hand with a single target quad count.
 ///
 /// `hand` is the current partial hand, represented as a bitvector.
@@ -17,37 +477,58 @@ use clap::{Parser, Subcommand};
 /// `max_diff_count` is the maximum allowed entry in `differences`.
 /// `next_index` is the next card to (maybe) add.
 /// `max_index` is the size of the deck.
-/// `cards_in_hand` is the number of cards in the final hand.
+/// `_cards_in_hand` is unused; it is kept for signature parity with the call sites.
 /// `cards_to_add` is the number of cards left to add.
 /// `quads` is the number of quads in the current partial hand times 3.
 /// `target_quads` is the desired number of quads, if not searching for the
 /// maximum.
-/// `best_score` is the highest number of quads in a hand found so far when
-/// searching for the maximum number of quads, or the lowest max card when
-/// searching for a specific number of quads.
-/// `best_table` is the hand that led to `best_score`.
+/// `best` is the global best result shared between workers: its score is the
+/// highest number of quads found so far when searching for the maximum, or the
+/// max card of a solution when searching for a specific number of quads. In
+/// target mode the search short-circuits on the first solution any worker
+/// finds, so the reported max card is that of some valid hand, not necessarily
+/// the minimal one (use `SearchAll`/`search_multi_generic` for minimal max cards).
+///
+/// `tt` is a transposition table used to prune isomorphic subtrees in
+/// maximize mode (see [`canonical_key`]).
+/// `node_best` accumulates the best number of (real) quads reachable from the
+/// current subtree; the caller uses it to populate `tt`.
+/// `split_remaining` is the number of recursion levels still to be split into
+/// independent [`Task`]s: while it is nonzero the top of the tree is expanded
+/// into `frontier` instead of being explored, and workers then run with it set
+/// to `usize::MAX` so they never defer.
 ///
 /// Returns `None` if searching for a specific number of quads and that has
 /// been achieved, and `Some(())` otherwise.
-fn search_inner(
-    hand: u128,
-    differences: [u8; 128],
+#[allow(clippy::too_many_arguments)]
+fn search_inner<B: Backend>(
+    hand: B::Hand,
+    differences: B::Diffs,
     min_diff_count: usize,
     max_diff_count: usize,
     next_index: usize,
     max_index: usize,
-    cards_in_hand: usize,
+    // Unused except to thread through the recursion, kept for signature parity
+    // with the call sites and `search_inner_multi`.
+    _cards_in_hand: usize,
     cards_to_add: usize,
     quads: u64,
     target_quads: Option<u64>,
-    best_score: &mut u64,
-    best_hand: &mut u128,
+    best: &SharedBest<B>,
+    tt: &mut TranspositionTable,
+    node_best: &mut u64,
+    split_remaining: usize,
+    frontier: &mut Vec<Task<B>>,
 ) -> Option<()> {
+    // Another worker already hit the target; unwind.
+    if target_quads.is_some() && best.found.load(Ordering::Relaxed) {
+        return None;
+    }
     // Nothing useful to do.
     if next_index + cards_to_add > max_index || cards_to_add == 0 {
         return Some(());
     }
-    let last_card_in_hand = hand.checked_ilog2().unwrap_or(0);
+    let last_card_in_hand = B::highest(&hand).unwrap_or(0);
     // The maximum dimension of the affine space used by a card in the hand,
     // indexed from 0.
     let max_dimension_used = last_card_in_hand.checked_ilog2().unwrap_or(0);
@@ -55,90 +536,133 @@ fn search_inner(
     // it's the first possible such card. (For example, if the highest card in
     // the hand is 3, there's no point adding any card above 4 since you could
     // equivalently add card 4 instead.)
-    let max_useful_card = (1 << max_dimension_used) * 2;
+    let max_useful_card = (1usize << max_dimension_used) * 2;
     if next_index > max_useful_card {
         return Some(());
     }
     if cards_to_add > 1 {
-        let mut differences2 = differences.clone();
-        let mut quads2 = quads;
-        let mut good = true;
-        for i in 0..next_index {
-            let difference = i ^ next_index;
-            if (hand >> i) & 1 == 1 {
-                // If there's a pair of cards in the hand with XOR x, and you
-                // add a new card which has XOR x with some other card in the
-                // hand, then those four cards form a quad. This counts each
-                // quad three times, so we divide by three later.
-                quads2 += differences2[difference] as u64;
-                differences2[difference] += 1;
-                // Don't try adding this card if that would violate max_diff_count.
-                if differences2[difference] as usize > max_diff_count {
-                    good = false;
-                    break;
+        // Defer this node to a worker thread once the top levels have been
+        // split off.
+        if split_remaining == 0 {
+            frontier.push(Task {
+                hand,
+                differences,
+                next_index,
+                cards_to_add,
+                quads,
+            });
+            return Some(());
+        }
+        // In maximize mode, consult the transposition table: if an
+        // equal-or-deeper entry for this node's signature cannot beat the best
+        // score found so far, the whole subtree is hopeless and can be skipped.
+        let key = if target_quads.is_none() {
+            let key = canonical_key::<B>(&differences, max_useful_card, cards_to_add, max_diff_count);
+            if let Some(entry) = tt.get(key) {
+                if entry.depth >= cards_to_add
+                    && quads / 3 + entry.bound <= best.score.load(Ordering::Relaxed)
+                {
+                    return Some(());
                 }
             }
-        }
+            Some(key)
+        } else {
+            None
+        };
+        // The best total real quads reachable from this node; seeded with the
+        // quads already on the board.
+        let mut subtree_best = quads / 3;
         // Try adding the card at `next_index`, if that doesn't create too many
-        // quads.
+        // quads (or violate max_diff_count).
         // Note that `quads2` triple-counts quads, so we need to multiply
         // `target` by 3.
-        if good && target_quads.is_none_or(|target| quads2 <= target * 3) {
-            search_inner(
-                hand | (1 << next_index),
-                differences2,
-                min_diff_count,
-                max_diff_count,
-                next_index + 1,
-                max_index,
-                cards_in_hand,
-                cards_to_add - 1,
-                quads2,
-                target_quads,
-                best_score,
-                best_hand,
-            )?;
+        if let Some((differences2, quads2)) =
+            B::add_card(&hand, &differences, next_index, max_diff_count, quads)
+        {
+            if target_quads.is_none_or(|target| quads2 <= target * 3) {
+                search_inner::<B>(
+                    B::with_card(&hand, next_index),
+                    differences2,
+                    min_diff_count,
+                    max_diff_count,
+                    next_index + 1,
+                    max_index,
+                    _cards_in_hand,
+                    cards_to_add - 1,
+                    quads2,
+                    target_quads,
+                    best,
+                    tt,
+                    &mut subtree_best,
+                    split_remaining.saturating_sub(1),
+                    frontier,
+                )?;
+            }
         }
         if next_index >= min_diff_count * 2 {
             // Try not adding the card at `next_index`.
-            search_inner(
+            search_inner::<B>(
                 hand,
                 differences,
                 min_diff_count,
                 max_diff_count,
                 next_index + 1,
                 max_index,
-                cards_in_hand,
+                _cards_in_hand,
                 cards_to_add,
                 quads,
                 target_quads,
-                best_score,
-                best_hand,
+                best,
+                tt,
+                &mut subtree_best,
+                split_remaining.saturating_sub(1),
+                frontier,
             )?;
         }
+        // Record this node's result so equivalent states can reuse it, and fold
+        // it into the parent's running best.
+        if let Some(key) = key {
+            tt.insert(
+                key,
+                Entry {
+                    depth: cards_to_add,
+                    bound: subtree_best.saturating_sub(quads / 3),
+                },
+            );
+        }
+        *node_best = (*node_best).max(subtree_best);
     } else {
         // One card left to add, so try all possibilities.
         for j in next_index..max_index.min(max_useful_card + 1) {
-            let mut quads2 = quads;
-            for i in 0..next_index {
-                if (hand >> i) & 1 == 1 {
-                    quads2 += differences[i ^ j] as u64;
-                }
-            }
+            let quads2 = B::quads_adding(&hand, &differences, j, next_index, quads);
             // Quads are triple-counted, so divide by 3.
             let real_quads = quads2 / 3;
+            *node_best = (*node_best).max(real_quads);
             if let Some(target) = target_quads {
                 let j2 = j as u64;
-                if real_quads == target && j2 < *best_score {
-                    *best_score = j2;
-                    *best_hand = hand | (1 << j);
+                if real_quads == target {
+                    // Record the solution (keeping the lowest max card among any
+                    // that land concurrently) and tell the other workers to stop.
+                    if j2 < best.score.fetch_min(j2, Ordering::Relaxed) {
+                        // Record the hand, unless a concurrent worker has
+                        // already stored one with an equal-or-lower max card:
+                        // otherwise `best.hand` and `best.score` could end up
+                        // describing different solutions.
+                        let mut best_hand = best.hand.lock().unwrap();
+                        if best.score.load(Ordering::Relaxed) == j2 {
+                            *best_hand = B::with_card(&hand, j);
+                        }
+                    }
+                    best.found.store(true, Ordering::Relaxed);
+                    // Exit early, since we found a solution.
+                    return None;
                 }
-                // Exit early, since we found a solution.
-                return None;
-            } else {
-                if real_quads > *best_score {
-                    *best_score = quads2 / 3;
-                    *best_hand = hand | (1 << j);
+            } else if real_quads > best.score.fetch_max(real_quads, Ordering::Relaxed) {
+                // We raised the global best; record the hand that did it, unless
+                // a concurrent worker has already gone higher.
+                let mut best_hand = best.hand.lock().unwrap();
+                if best.score.load(Ordering::Relaxed) == real_quads {
+                    *best_hand = B::with_card(&hand, j);
                 }
             }
         }
@@ -154,18 +678,21 @@ fn search_inner(
 /// `max_diff_count` is the maximum allowed entry in `differences`.
 /// `next_index` is the next card to (maybe) add.
 /// `max_index` is the size of the deck.
-/// `cards_in_hand` is the number of cards in the final hand.
+/// `_cards_in_hand` is unused; it is kept for signature parity with the call sites.
 /// `cards_to_add` is the number of cards left to add.
 /// `quads` is the number of quads in the current partial hand times 3.
 /// `best_scores` contains the lowest max card for each quad count.
-fn search_inner_multi(
-    hand: u128,
-    differences: [u8; 128],
+#[allow(clippy::too_many_arguments)]
+fn search_inner_multi<B: Backend>(
+    hand: B::Hand,
+    differences: B::Diffs,
     min_diff_count: usize,
     max_diff_count: usize,
     next_index: usize,
     max_index: usize,
-    cards_in_hand: usize,
+    // Unused except to thread through the recursion, kept for signature parity
+    // with the call sites and `search_inner`.
+    _cards_in_hand: usize,
     cards_to_add: usize,
     quads: u64,
     best_scores: &mut Vec<u64>,
@@ -174,7 +701,7 @@ fn search_inner_multi(
     if next_index + cards_to_add > max_index || cards_to_add == 0 {
         return;
     }
-    let last_card_in_hand = hand.checked_ilog2().unwrap_or(0);
+    let last_card_in_hand = B::highest(&hand).unwrap_or(0);
     // The maximum dimension of the affine space used by a card in the hand,
     // indexed from 0.
     let max_dimension_used = last_card_in_hand.checked_ilog2().unwrap_or(0);
@@ -182,40 +709,23 @@ fn search_inner_multi(
     // it's the first possible such card. (For example, if the highest card in
     // the hand is 3, there's no point adding any card above 4 since you could
     // equivalently add card 4 instead.)
-    let max_useful_card = (1 << max_dimension_used) * 2;
+    let max_useful_card = (1usize << max_dimension_used) * 2;
     if next_index > max_useful_card {
         return;
     }
     if cards_to_add > 1 {
-        let mut differences2 = differences.clone();
-        let mut quads2 = quads;
-        let mut good = true;
-        for i in 0..next_index {
-            let difference = i ^ next_index;
-            if (hand >> i) & 1 == 1 {
-                // If there's a pair of cards in the hand with XOR x, and you
-                // add a new card which has XOR x with some other card in the
-                // hand, then those four cards form a quad. This counts each
-                // quad three times, so we divide by three later.
-                quads2 += differences2[difference] as u64;
-                differences2[difference] += 1;
-                // Don't try adding this card if that would violate max_diff_count.
-                if differences2[difference] as usize > max_diff_count {
-                    good = false;
-                    break;
-                }
-            }
-        }
         // Try adding the card at `next_index`.
-        if good {
-            search_inner_multi(
-                hand | (1 << next_index),
+        if let Some((differences2, quads2)) =
+            B::add_card(&hand, &differences, next_index, max_diff_count, quads)
+        {
+            search_inner_multi::<B>(
+                B::with_card(&hand, next_index),
                 differences2,
                 min_diff_count,
                 max_diff_count,
                 next_index + 1,
                 max_index,
-                cards_in_hand,
+                _cards_in_hand,
                 cards_to_add - 1,
                 quads2,
                 best_scores,
@@ -223,14 +733,14 @@ fn search_inner_multi(
         }
         if next_index >= min_diff_count * 2 {
             // Try not adding the card at `next_index`.
-            search_inner_multi(
+            search_inner_multi::<B>(
                 hand,
                 differences,
                 min_diff_count,
                 max_diff_count,
                 next_index + 1,
                 max_index,
-                cards_in_hand,
+                _cards_in_hand,
                 cards_to_add,
                 quads,
                 best_scores,
@@ -239,12 +749,7 @@ fn search_inner_multi(
     } else {
         // One card left to add, so try all possibilities.
         for j in next_index..max_index.min(max_useful_card + 1) {
-            let mut quads2 = quads;
-            for i in 0..next_index {
-                if (hand >> i) & 1 == 1 {
-                    quads2 += differences[i ^ j] as u64;
-                }
-            }
+            let quads2 = B::quads_adding(&hand, &differences, j, next_index, quads);
             // Quads are triple-counted, so divide by 3.
             let real_quads = (quads2 / 3) as usize;
             // Don't overflow.
@@ -258,113 +763,196 @@ fn search_inner_multi(
     };
 }
 
-/// Search for a hand.
+/// Run one root configuration of [`search_inner`], parallelized across
+/// `threads` worker threads that share `best`.
+///
+/// The top few recursion levels are split into independent [`Task`]s; a pool of
+/// workers then pulls tasks from a shared index (as `SearchAll` does), each
+/// keeping its own transposition table and pruning against the shared `best`.
+#[allow(clippy::too_many_arguments)]
+fn run_root<B: Backend>(
+    min_diff_count: usize,
+    max_diff_count: usize,
+    max_index: usize,
+    cards_in_hand: usize,
+    prefix: &Prefix<B>,
+    target_quads: Option<u64>,
+    best: &SharedBest<B>,
+    threads: usize,
+) {
+    // Each config starts its own hunt for the target, so clear any earlier
+    // "found" flag before it runs.
+    best.found.store(false, Ordering::Relaxed);
+    // Split the top of the tree into tasks, expanding one level deeper until
+    // there is enough work to keep every thread busy.
+    let mut frontier = Vec::new();
+    if threads > 1 {
+        let mut split_levels = 1;
+        loop {
+            frontier.clear();
+            // The transposition table is useless for the shallow collection
+            // pass, so give it zero capacity.
+            let mut tt = TranspositionTable::new(0);
+            let mut node_best = 0;
+            search_inner::<B>(
+                prefix.hand.clone(),
+                prefix.differences.clone(),
+                min_diff_count,
+                max_diff_count,
+                prefix.next_index,
+                max_index,
+                cards_in_hand,
+                prefix.cards_to_add,
+                prefix.quads,
+                target_quads,
+                best,
+                &mut tt,
+                &mut node_best,
+                split_levels,
+                &mut frontier,
+            );
+            if best.found.load(Ordering::Relaxed)
+                || frontier.len() >= threads * 4
+                || split_levels >= cards_in_hand
+            {
+                break;
+            }
+            split_levels += 1;
+        }
+    }
+    // A target solution may have turned up during collection, in which case we
+    // are done; if there is no work worth parallelizing, fall back to a single
+    // search.
+    if best.found.load(Ordering::Relaxed) {
+        return;
+    }
+    if threads <= 1 || frontier.is_empty() {
+        let mut tt = TranspositionTable::new(TT_CAPACITY);
+        let mut node_best = 0;
+        search_inner::<B>(
+            prefix.hand.clone(),
+            prefix.differences.clone(),
+            min_diff_count,
+            max_diff_count,
+            prefix.next_index,
+            max_index,
+            cards_in_hand,
+            prefix.cards_to_add,
+            prefix.quads,
+            target_quads,
+            best,
+            &mut tt,
+            &mut node_best,
+            usize::MAX,
+            &mut Vec::new(),
+        );
+        return;
+    }
+    let frontier = &frontier;
+    let next_task = AtomicUsize::new(0);
+    thread::scope(|s| {
+        for _ in 0..threads {
+            s.spawn(|| {
+                let mut tt = TranspositionTable::new(TT_CAPACITY);
+                let mut scratch = Vec::new();
+                loop {
+                    if target_quads.is_some() && best.found.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let index = next_task.fetch_add(1, Ordering::Relaxed);
+                    let Some(task) = frontier.get(index) else {
+                        break;
+                    };
+                    let mut node_best = 0;
+                    search_inner::<B>(
+                        task.hand.clone(),
+                        task.differences.clone(),
+                        min_diff_count,
+                        max_diff_count,
+                        task.next_index,
+                        max_index,
+                        cards_in_hand,
+                        task.cards_to_add,
+                        task.quads,
+                        target_quads,
+                        best,
+                        &mut tt,
+                        &mut node_best,
+                        usize::MAX,
+                        &mut scratch,
+                    );
+                }
+            });
+        }
+    });
+}
+
+/// Search for a hand with the backend `B`.
 ///
 /// `cards_in_deck` is the size of the deck.
 /// `cards_in_hand` is the size of the target hand.
 /// `target_quads` is the desired number of quads, if not searching for the
 /// maximum.
+/// `threads` is the number of worker threads to split the search across.
+/// `seed` optionally locks a set of cards into the initial state, so the search
+/// only explores extensions of that prefix.
 ///
 /// Returns the best hand and its score.
-fn search(cards_in_deck: usize, cards_in_hand: usize, target_quads: Option<u64>) -> (u128, u64) {
-    let mut best_hand = (1 << cards_in_hand) - 1;
-    let mut best_score = if target_quads == None {
-        0
-    } else {
-        cards_in_deck as u64
+fn search_generic<B: Backend>(
+    cards_in_deck: usize,
+    cards_in_hand: usize,
+    target_quads: Option<u64>,
+    threads: usize,
+    seed: Option<u128>,
+) -> (B::Hand, u64) {
+    let prefix = match seed {
+        Some(seed) => Prefix::<B>::from_seed(seed, cards_in_hand),
+        None => Prefix::<B>::empty(cards_in_hand),
+    };
+    let best = SharedBest::<B> {
+        score: AtomicU64::new(if target_quads.is_none() {
+            0
+        } else {
+            cards_in_deck as u64
+        }),
+        hand: Mutex::new(B::low_bits(cards_in_hand)),
+        found: AtomicBool::new(false),
     };
-    // let min_max_diff_count = match target_quads {
-    //     None => 3,
-    //     Some(target) => {
-    //         if target > (cards_in_hand * (cards_in_hand + 1) / 12) as u64 {
-    //             3
-    //         } else if target > 0 {
-    //             2
-    //         } else {
-    //             1
-    //         }
-    //     }
-    // }
-    // .min(cards_in_hand / 2);
-    // for max_diff_count in min_max_diff_count..=(cards_in_hand / 2) {
-    //     search_inner(
-    //         0,
-    //         [0; 128],
-    //         max_diff_count,
-    //         0,
-    //         cards_in_deck,
-    //         cards_in_hand,
-    //         cards_in_hand,
-    //         0,
-    //         target_quads,
-    //         &mut best_score,
-    //         &mut best_hand,
-    //     );
-    // }
     if let Some(target) = target_quads {
         if target == 0 {
-            search_inner(
-                0,
-                [0; 128],
-                1,
-                1,
-                0,
-                cards_in_deck,
-                cards_in_hand,
-                cards_in_hand,
-                0,
-                target_quads,
-                &mut best_score,
-                &mut best_hand,
-            );
+            run_root(1, 1, cards_in_deck, cards_in_hand, &prefix, target_quads, &best, threads);
         }
         if target <= (cards_in_hand * (cards_in_hand + 1) / 12) as u64 {
-            search_inner(
-                0,
-                [0; 128],
-                2,
-                2,
-                0,
-                cards_in_deck,
-                cards_in_hand,
-                cards_in_hand,
-                0,
-                target_quads,
-                &mut best_score,
-                &mut best_hand,
-            );
+            run_root(2, 2, cards_in_deck, cards_in_hand, &prefix, target_quads, &best, threads);
         }
     }
-    search_inner(
-        0,
-        [0; 128],
+    run_root(
         3,
         cards_in_deck / 2,
-        0,
         cards_in_deck,
         cards_in_hand,
-        cards_in_hand,
-        0,
+        &prefix,
         target_quads,
-        &mut best_score,
-        &mut best_hand,
+        &best,
+        threads,
     );
-    (best_hand, best_score)
+    let best_hand = best.hand.lock().unwrap().clone();
+    (best_hand, best.score.into_inner())
 }
 
-/// Search for many hands.
+/// Search for many hands with the backend `B`.
 ///
 /// `cards_in_deck` is the size of the deck.
 /// `cards_in_hand` is the size of the target hands.
 ///
 /// The `n`th element of the result is the maximum card used in a hand with `n`
 /// quads if one exists, or `cards_in_deck` otherwise.
-fn search_multi(mut cards_in_deck: usize, cards_in_hand: usize) -> Vec<u64> {
+fn search_multi_generic<B: Backend>(mut cards_in_deck: usize, cards_in_hand: usize) -> Vec<u64> {
     let mut ret = vec![];
     while cards_in_deck > 0 && cards_in_deck >= cards_in_hand {
-        search_inner_multi(
-            0,
-            [0; 128],
+        search_inner_multi::<B>(
+            B::empty_hand(),
+            B::empty_diffs(),
             1,
             1,
             0,
@@ -374,9 +962,9 @@ fn search_multi(mut cards_in_deck: usize, cards_in_hand: usize) -> Vec<u64> {
             0,
             &mut ret,
         );
-        search_inner_multi(
-            0,
-            [0; 128],
+        search_inner_multi::<B>(
+            B::empty_hand(),
+            B::empty_diffs(),
             2,
             2,
             0,
@@ -386,9 +974,9 @@ fn search_multi(mut cards_in_deck: usize, cards_in_hand: usize) -> Vec<u64> {
             0,
             &mut ret,
         );
-        search_inner_multi(
-            0,
-            [0; 128],
+        search_inner_multi::<B>(
+            B::empty_hand(),
+            B::empty_diffs(),
             3,
             cards_in_deck / 2,
             0,
@@ -405,6 +993,155 @@ fn search_multi(mut cards_in_deck: usize, cards_in_hand: usize) -> Vec<u64> {
     ret
 }
 
+/// Count the quads in `hand` directly, as an independent reference for the
+/// incremental bookkeeping in `search_inner`.
+///
+/// A quad is an unordered set of four cards `{a, b, c, d}` whose XOR is zero
+/// (equivalently `a ^ b == c ^ d`). This counts them with a plain
+/// four-combination loop, which is all the `verify`/`score` subcommands and the
+/// tests need.
+fn brute_force_quads(hand: u128) -> u64 {
+    let cards: Vec<u32> = (0..128).filter(|&i| (hand >> i) & 1 == 1).collect();
+    let mut quads = 0;
+    for a in 0..cards.len() {
+        for b in (a + 1)..cards.len() {
+            for c in (b + 1)..cards.len() {
+                for d in (c + 1)..cards.len() {
+                    if cards[a] ^ cards[b] ^ cards[c] ^ cards[d] == 0 {
+                        quads += 1;
+                    }
+                }
+            }
+        }
+    }
+    quads
+}
+
+/// Parse a hand bitmask, accepting either a `0x`-prefixed hexadecimal value or a
+/// plain decimal one.
+fn parse_bitmask(text: &str) -> Result<u128, std::num::ParseIntError> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u128::from_str_radix(hex, 16),
+        None => text.parse(),
+    }
+}
+
+/// Parse a hand in the compact notation: either a bitmask (see [`parse_bitmask`])
+/// or a comma-separated list of card indices such as `0,1,2,4,8`.
+fn parse_hand(text: &str) -> Result<u128, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        // The empty hand, the canonical form of which is the empty string.
+        Ok(0)
+    } else if text.contains(',') {
+        let mut hand = 0;
+        for part in text.split(',') {
+            let part = part.trim();
+            if part.is_empty() {
+                continue;
+            }
+            let index: u32 = part
+                .parse()
+                .map_err(|err| format!("invalid card index {part:?}: {err}"))?;
+            if index >= 128 {
+                return Err(format!("card index {index} is out of range (max 127)"));
+            }
+            hand |= 1 << index;
+        }
+        Ok(hand)
+    } else {
+        parse_bitmask(text).map_err(|err| format!("invalid hand {text:?}: {err}"))
+    }
+}
+
+/// Format a hand as a comma-terminated list of its card indices, the canonical
+/// form accepted by [`parse_hand`].
+///
+/// Each index carries a trailing comma (so `{5}` formats as `5,` and the empty
+/// hand as the empty string) to keep the list form unambiguous: a bare `5`
+/// would otherwise parse back as the bitmask `5`, not the single card `5`.
+fn format_hand(hand: u128) -> String {
+    (0..128)
+        .filter(|i| (hand >> i) & 1 == 1)
+        .map(|i| format!("{i},"))
+        .collect()
+}
+
+/// Run the `Search` subcommand with the backend `B`, searching then printing the
+/// result with a bitset grid laid out for `B`'s width.
+fn run_search<B: Backend>(
+    cards_in_hand: usize,
+    cards_in_deck: usize,
+    target_quads: Option<u64>,
+    threads: usize,
+    seed: Option<u128>,
+) {
+    let start = Instant::now();
+    let (best_hand, best_score) =
+        search_generic::<B>(cards_in_deck, cards_in_hand, target_quads, threads, seed);
+    println!("Time: {:?}", start.elapsed());
+    if target_quads.is_none() {
+        println!("Max quads: {best_score}");
+    } else if best_score > 0 {
+        println!("Found a hand.");
+        if let Some(max_card) = B::highest(&best_hand) {
+            println!("Max card used: {max_card}");
+        }
+    } else {
+        println!("No hand found.");
+    }
+    println!("Best hand:");
+    // Lay the hand out sixteen cards to a row, as many rows as the deck needs.
+    for row in 0..cards_in_deck.div_ceil(16) {
+        for col in 0..16 {
+            let i = row * 16 + col;
+            if i >= cards_in_deck {
+                break;
+            }
+            print!("{}", B::contains(&best_hand, i) as u8);
+        }
+        println!();
+    }
+}
+
+/// Run the `SearchAll` subcommand with the backend `B`.
+fn run_search_all<B: Backend>(
+    initial_cards_in_hand: usize,
+    cards_in_deck: usize,
+    max_cards_in_hand: usize,
+) {
+    let threads = thread::available_parallelism()
+        .map(|x| x.get())
+        .unwrap_or(1);
+    println!("Threads: {threads}");
+    let cards_in_hand_atomic = AtomicUsize::new(initial_cards_in_hand);
+    let print_guard = Mutex::new(());
+    thread::scope(|s| {
+        for _tid in 0..threads {
+            s.spawn(|| loop {
+                let cards_in_hand = cards_in_hand_atomic.fetch_add(1, Ordering::Relaxed);
+                if cards_in_hand > max_cards_in_hand {
+                    break;
+                }
+                let res = search_multi_generic::<B>(cards_in_deck, cards_in_hand);
+                let guard = print_guard.lock().unwrap();
+                println!("Hand size {cards_in_hand}, deck size {cards_in_deck}:");
+                for (j, &max) in res.iter().enumerate() {
+                    if max < cards_in_deck as u64 {
+                        println!("{j} quads with max card {max}");
+                    }
+                }
+                drop(guard);
+            });
+        }
+    })
+}
+
+/// The widest deck the engine supports. Above this the difference histogram and
+/// bitset would grow unreasonably large; decks up to here pick a backing width
+/// at runtime from `cards_in_deck`.
+const MAX_DECK: usize = 1024;
+
 #[derive(Parser)]
 struct Cli {
     #[command(subcommand)]
@@ -418,23 +1155,91 @@ enum Commands {
     Search {
         /// Number of cards in the hand (increase this slowly, start with 9)
         cards_in_hand: usize,
-        /// Number of cards in the deck (max 128)
+        /// Number of cards in the deck (max 1024)
         #[arg(default_value_t = 128)]
         cards_in_deck: usize,
         /// Number of quads the hand should have. If not specified, searches for the maximum possible number of quads.
         target_quads: Option<u64>,
+        /// Number of worker threads to split the search across. Defaults to the
+        /// number of available cores.
+        #[arg(long)]
+        threads: Option<usize>,
+        /// Lock a set of cards into the hand and only search for completions of
+        /// it. Accepts a bitmask or a comma-separated list of card indices.
+        #[arg(long, value_parser = parse_hand)]
+        seed: Option<u128>,
     },
     /// Check lots of hand sizes
     #[command(arg_required_else_help = true)]
     SearchAll {
         /// Initial hand size
         initial_cards_in_hand: usize,
-        /// Number of cards in the deck (max 128)
+        /// Number of cards in the deck (max 1024)
         #[arg(default_value_t = 128)]
         cards_in_deck: usize,
         /// Maximum hand size
         max_cards_in_hand: Option<usize>,
     },
+    /// Count the quads in a given hand using the brute-force counter
+    #[command(arg_required_else_help = true)]
+    Verify {
+        /// The hand as a bitmask (hex with a `0x` prefix, or decimal)
+        #[arg(value_parser = parse_bitmask)]
+        hand: u128,
+    },
+    /// Score a hand given in the compact notation
+    #[command(arg_required_else_help = true)]
+    Score {
+        /// The hand as a bitmask or a comma-separated list of card indices
+        #[arg(value_parser = parse_hand)]
+        hand: u128,
+    },
+}
+
+/// Dispatch to `run_search` with the narrowest backend that covers `cards_in_deck`.
+fn search_command(
+    cards_in_hand: usize,
+    cards_in_deck: usize,
+    target_quads: Option<u64>,
+    threads: usize,
+    seed: Option<u128>,
+) {
+    match cards_in_deck {
+        0..=128 => run_search::<U128>(cards_in_hand, cards_in_deck, target_quads, threads, seed),
+        129..=256 => {
+            run_search::<Wide<4, 256>>(cards_in_hand, cards_in_deck, target_quads, threads, seed)
+        }
+        257..=512 => {
+            run_search::<Wide<8, 512>>(cards_in_hand, cards_in_deck, target_quads, threads, seed)
+        }
+        _ => run_search::<Wide<16, 1024>>(
+            cards_in_hand,
+            cards_in_deck,
+            target_quads,
+            threads,
+            seed,
+        ),
+    }
+}
+
+/// Dispatch to `run_search_all` with the narrowest backend that covers `cards_in_deck`.
+fn search_all_command(
+    initial_cards_in_hand: usize,
+    cards_in_deck: usize,
+    max_cards_in_hand: usize,
+) {
+    match cards_in_deck {
+        0..=128 => run_search_all::<U128>(initial_cards_in_hand, cards_in_deck, max_cards_in_hand),
+        129..=256 => {
+            run_search_all::<Wide<4, 256>>(initial_cards_in_hand, cards_in_deck, max_cards_in_hand)
+        }
+        257..=512 => {
+            run_search_all::<Wide<8, 512>>(initial_cards_in_hand, cards_in_deck, max_cards_in_hand)
+        }
+        _ => {
+            run_search_all::<Wide<16, 1024>>(initial_cards_in_hand, cards_in_deck, max_cards_in_hand)
+        }
+    }
 }
 
 fn main() {
@@ -443,67 +1248,257 @@ fn main() {
             cards_in_hand,
             cards_in_deck,
             target_quads,
+            threads,
+            seed,
         } => {
-            if cards_in_deck > 128 {
-                println!("The maximum supported deck size is 128.");
+            if cards_in_deck > MAX_DECK {
+                println!("The maximum supported deck size is {MAX_DECK}.");
                 return;
             }
             if cards_in_hand > cards_in_deck {
                 println!("The requested hand is bigger than the deck.");
                 return;
             }
-            let start = Instant::now();
-            let (best_hand, best_score) = search(cards_in_deck, cards_in_hand, target_quads);
-            println!("Time: {:?}", start.elapsed());
-            if target_quads.is_none() {
-                println!("Max quads: {best_score}");
-            } else if best_score > 0 {
-                println!("Found a hand.");
-                if let Some(max_dim) = best_hand.checked_ilog2() {
-                    println!("Max card used: {max_dim}");
+            if let Some(seed) = seed {
+                if seed.count_ones() as usize > cards_in_hand {
+                    println!("The seed has more cards than the hand.");
+                    return;
                 }
-            } else {
-                println!("No hand found.");
-            }
-            println!("Best hand:");
-            for i in 0..8 {
-                for j in 0..16 {
-                    print!("{}", (best_hand >> (i * 16 + j)) & 1);
+                if seed.checked_ilog2().is_some_and(|top| top as usize >= cards_in_deck) {
+                    println!("The seed uses a card outside the deck.");
+                    return;
                 }
-                println!("");
             }
+            let threads = threads.unwrap_or_else(|| {
+                thread::available_parallelism()
+                    .map(|x| x.get())
+                    .unwrap_or(1)
+            });
+            println!("Threads: {threads}");
+            search_command(cards_in_hand, cards_in_deck, target_quads, threads, seed);
         }
         Commands::SearchAll {
             initial_cards_in_hand,
             cards_in_deck,
             max_cards_in_hand,
         } => {
+            if cards_in_deck > MAX_DECK {
+                println!("The maximum supported deck size is {MAX_DECK}.");
+                return;
+            }
             let max_cards_in_hand = max_cards_in_hand.unwrap_or(cards_in_deck);
-            let threads = thread::available_parallelism()
-                .map(|x| x.get())
-                .unwrap_or(1);
-            println!("Threads: {threads}");
-            let cards_in_hand_atomic = AtomicUsize::new(initial_cards_in_hand);
-            let print_guard = Mutex::new(());
-            thread::scope(|s| {
-                for _tid in 0..threads {
-                    s.spawn(|| loop {
-                        let cards_in_hand = cards_in_hand_atomic.fetch_add(1, Ordering::Relaxed);
-                        if cards_in_hand > max_cards_in_hand {
-                            break;
-                        }
-                        let res = search_multi(cards_in_deck, cards_in_hand);
-                        let guard = print_guard.lock().unwrap();
-                        println!("Hand size {cards_in_hand}, deck size {cards_in_deck}:");
-                        for (j, &max) in res.iter().enumerate() {
-                            if max < cards_in_deck as u64 {
-                                println!("{j} quads with max card {max}");
-                            }
-                        }
-                        drop(guard);
-                    });
+            search_all_command(initial_cards_in_hand, cards_in_deck, max_cards_in_hand);
+        }
+        Commands::Verify { hand } => {
+            let quads = brute_force_quads(hand);
+            println!("Quads: {quads}");
+            if let Some(max_card) = hand.checked_ilog2() {
+                println!("Max card used: {max_card}");
+            }
+        }
+        Commands::Score { hand } => {
+            println!("Hand: {}", format_hand(hand));
+            println!("Quads: {}", brute_force_quads(hand));
+            if let Some(max_card) = hand.checked_ilog2() {
+                println!("Max card used: {max_card}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Search for a hand, specialized to decks of at most 128 cards.
+    fn search(
+        cards_in_deck: usize,
+        cards_in_hand: usize,
+        target_quads: Option<u64>,
+        threads: usize,
+        seed: Option<u128>,
+    ) -> (u128, u64) {
+        search_generic::<U128>(cards_in_deck, cards_in_hand, target_quads, threads, seed)
+    }
+
+    /// Search for many hands, specialized to decks of at most 128 cards.
+    fn search_multi(cards_in_deck: usize, cards_in_hand: usize) -> Vec<u64> {
+        search_multi_generic::<U128>(cards_in_deck, cards_in_hand)
+    }
+
+    /// A tiny linear-congruential generator, so the property tests draw the same
+    /// parameters on every run without pulling in a PRNG dependency.
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self) -> u64 {
+            self.0 = self
+                .0
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            self.0
+        }
+
+        /// A value in `lo..hi`.
+        fn range(&mut self, lo: usize, hi: usize) -> usize {
+            lo + (self.next() >> 33) as usize % (hi - lo)
+        }
+    }
+
+    /// Count quads via the histogram identity: two distinct cards never XOR to
+    /// zero, so each quad shows up as three pairs of pairs sharing an XOR value.
+    fn histogram_quads(hand: u128) -> u64 {
+        let cards: Vec<u32> = (0..128).filter(|&i| (hand >> i) & 1 == 1).collect();
+        let mut counts: HashMap<u32, u64> = HashMap::new();
+        for a in 0..cards.len() {
+            for b in (a + 1)..cards.len() {
+                *counts.entry(cards[a] ^ cards[b]).or_default() += 1;
+            }
+        }
+        let pairs_of_pairs: u64 = counts.values().map(|&c| c * (c - 1) / 2).sum();
+        pairs_of_pairs / 3
+    }
+
+    #[test]
+    fn hand_notation_round_trips() {
+        for hand in [0u128, 0b1101, (1 << 9) - 1, 0x1234_5678] {
+            assert_eq!(parse_hand(&format_hand(hand)), Ok(hand), "hand {hand:#x}");
+        }
+        // The two notations agree.
+        assert_eq!(parse_hand("0,1,2,4,8"), Ok(0b1_0001_0111));
+        assert_eq!(parse_hand("0x17"), parse_hand("23"));
+        assert!(parse_hand("0,1,200").is_err());
+    }
+
+    #[test]
+    fn seed_only_explores_completions() {
+        // Seeding with a known-good sub-hand must keep the seed cards, report a
+        // score its hand really has, and never do worse than the seed alone.
+        let seed = parse_hand("0,1,2,3").unwrap();
+        let (hand, score) = search(16, 6, None, 1, Some(seed));
+        assert_eq!(hand & seed, seed, "seed cards must remain in the hand");
+        assert_eq!(brute_force_quads(hand), score);
+        assert!(score >= brute_force_quads(seed));
+    }
+
+    #[test]
+    fn brute_force_matches_histogram() {
+        for hand in [0b1111u128, 0b10110101, (1 << 9) - 1, 0x1234_5678, 0xffff] {
+            assert_eq!(brute_force_quads(hand), histogram_quads(hand), "hand {hand:#x}");
+        }
+    }
+
+    #[test]
+    fn maximize_hand_matches_its_score() {
+        let mut rng = Lcg(0x1234_5678);
+        for _ in 0..40 {
+            let cards_in_deck = 1 << rng.range(2, 5); // 4, 8, or 16
+            let cards_in_hand = rng.range(4, (cards_in_deck + 1).min(9));
+            let (hand, score) = search(cards_in_deck, cards_in_hand, None, 1, None);
+            assert_eq!(
+                brute_force_quads(hand),
+                score,
+                "deck {cards_in_deck} hand {cards_in_hand}"
+            );
+            assert_eq!(hand.count_ones() as usize, cards_in_hand);
+            assert!((hand.checked_ilog2().unwrap() as usize) < cards_in_deck);
+        }
+    }
+
+    #[test]
+    fn target_solutions_are_valid() {
+        let mut rng = Lcg(0xDEAD_BEEF);
+        for _ in 0..40 {
+            let cards_in_deck = 1 << rng.range(3, 6); // 8, 16, or 32
+            let cards_in_hand = rng.range(4, (cards_in_deck + 1).min(10));
+            let max_quads = search(cards_in_deck, cards_in_hand, None, 1, None).1;
+            let target = rng.range(0, max_quads as usize + 1) as u64;
+            let (hand, max_card) = search(cards_in_deck, cards_in_hand, Some(target), 1, None);
+            // A reported solution must genuinely have the target quad count, fill
+            // the hand, fit in the deck, and agree about its max card.
+            if (max_card as usize) < cards_in_deck {
+                assert_eq!(brute_force_quads(hand), target);
+                assert_eq!(hand.count_ones() as usize, cards_in_hand);
+                assert_eq!(hand.checked_ilog2().unwrap() as u64, max_card);
+            }
+        }
+    }
+
+    #[test]
+    fn multi_max_cards_are_achievable() {
+        let mut rng = Lcg(0xF00D);
+        for _ in 0..15 {
+            let cards_in_deck = 1 << rng.range(3, 6); // 8, 16, or 32
+            let cards_in_hand = rng.range(4, (cards_in_deck + 1).min(10));
+            for (quads, &max_card) in search_multi(cards_in_deck, cards_in_hand).iter().enumerate() {
+                if max_card as usize >= cards_in_deck {
+                    continue;
                 }
-            })
+                // Each quad count `search_multi` reports as reachable must be
+                // realized by a genuinely valid hand from the targeted search.
+                // The targeted search only promises *a* solution, not the
+                // minimal max card, so its max card can be no smaller than the
+                // minimum `search_multi` found.
+                let (hand, score) = search(cards_in_deck, cards_in_hand, Some(quads as u64), 1, None);
+                assert_eq!(
+                    brute_force_quads(hand),
+                    quads as u64,
+                    "deck {cards_in_deck} hand {cards_in_hand} quads {quads}"
+                );
+                assert_eq!(hand.count_ones() as usize, cards_in_hand);
+                assert!(score >= max_card, "deck {cards_in_deck} hand {cards_in_hand} quads {quads}");
+            }
+        }
+    }
+
+    /// Regression guard for the multi-mode minima at hand size 9: 3 quads at deck
+    /// 32 has max card 20, and 0 quads at deck 64 has max card 39. These values
+    /// must not drift.
+    #[test]
+    fn multi_minima_match_known_baseline() {
+        assert_eq!(search_multi(32, 9)[3], 20, "deck 32 hand 9, 3 quads");
+        assert_eq!(search_multi(64, 9)[0], 39, "deck 64 hand 9, 0 quads");
+    }
+
+    /// For hand sizes of 9 and 10, every quad count `search_multi` reports as
+    /// reachable must be realized by a valid hand from the targeted search.
+    #[test]
+    fn multi_counts_are_achievable_large_hands() {
+        for cards_in_hand in 9..=10 {
+            for (quads, &max_card) in search_multi(32, cards_in_hand).iter().enumerate() {
+                if max_card as usize >= 32 {
+                    continue;
+                }
+                let (hand, score) = search(32, cards_in_hand, Some(quads as u64), 1, None);
+                assert_eq!(
+                    brute_force_quads(hand),
+                    quads as u64,
+                    "hand {cards_in_hand} quads {quads}"
+                );
+                assert_eq!(hand.count_ones() as usize, cards_in_hand);
+                assert!(score >= max_card, "hand {cards_in_hand} quads {quads}");
+            }
+        }
+    }
+
+    /// The wide backend must agree with the `u128` fast path on decks both can
+    /// represent, so generalizing the state didn't change any answers.
+    #[test]
+    fn wide_backend_matches_narrow() {
+        let mut rng = Lcg(0x5EED);
+        for _ in 0..20 {
+            let cards_in_deck = 1 << rng.range(2, 5); // 4, 8, or 16
+            let cards_in_hand = rng.range(4, (cards_in_deck + 1).min(9));
+            let (_, narrow) = search_generic::<U128>(cards_in_deck, cards_in_hand, None, 1, None);
+            let (wide_hand, wide) =
+                search_generic::<Wide<4, 256>>(cards_in_deck, cards_in_hand, None, 1, None);
+            assert_eq!(narrow, wide, "deck {cards_in_deck} hand {cards_in_hand}");
+            // The wide hand's set cards must reproduce the reported score.
+            let bitmask: u128 = Wide::<4, 256>::cards(&wide_hand)
+                .into_iter()
+                .map(|c| 1u128 << c)
+                .sum();
+            assert_eq!(brute_force_quads(bitmask), wide);
         }
     }
 }